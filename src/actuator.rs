@@ -1,8 +1,7 @@
 use std::collections::BTreeMap;
 use std::fmt;
-use std::num;
 use std::result;
-use std::str;
+use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 
 use time::*;
@@ -11,12 +10,18 @@ use utils::*;
 
 use rpc::InvalArgError as IAE;
 use rpc::Error::*;
+use sensor::OverrunBound;
 pub type Result<T> = result::Result<T, ::rpc::Error>;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum ActuatorType {
     Toggle,
     FloatValue { min: f64, max: f64 },
+    /// A named discrete state, e.g. an HVAC mode selector.
+    Enum { variants: Vec<String> },
+    /// A float quantized to whole steps of `step` above `min` within
+    /// `[min, max]`, e.g. a multi-speed fan.
+    Stepped { min: f64, max: f64, step: f64 },
 }
 
 impl fmt::Display for ActuatorType {
@@ -24,14 +29,19 @@ impl fmt::Display for ActuatorType {
         match self {
             ActuatorType::Toggle => write!(f, "Toggle"),
             ActuatorType::FloatValue { min, max } => write!(f, "Float [{}, {}]", min, max),
+            ActuatorType::Enum { variants } => write!(f, "Enum [{}]", variants.join(", ")),
+            ActuatorType::Stepped { min, max, step } =>
+                write!(f, "Stepped [{}, {}] step {}", min, max, step),
         }
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub enum ActuatorState {
     Toggle(bool),
     FloatValue(f64),
+    Enum(String),
+    Stepped(f64),
 }
 
 impl fmt::Display for ActuatorState {
@@ -39,22 +49,99 @@ impl fmt::Display for ActuatorState {
         match self {
             ActuatorState::Toggle(value) => write!(f, "{}", if *value { "On" } else { "Off " }),
             ActuatorState::FloatValue(value) => write!(f, "{}", value),
+            ActuatorState::Enum(variant) => write!(f, "{}", variant),
+            ActuatorState::Stepped(value) => write!(f, "{}", value),
         }
     }
 }
 
-impl str::FromStr for ActuatorState {
-    type Err = num::ParseFloatError;
+/// Converts a user-supplied string into a validated `ActuatorState` for a
+/// particular `ActuatorType`, rejecting enum values outside `variants`
+/// and stepped values off their step boundary. This is the only string
+/// parsing entry point for `ActuatorState` — unlike a blind "on"/"off"/
+/// float parse, it knows the declared type it's converting for, so a
+/// stepped actuator's numeric string can't bypass step/bounds validation
+/// by landing in an untyped `FloatValue`.
+pub struct Conversion<'a> {
+    actuator_type: &'a ActuatorType,
+}
+
+impl<'a> Conversion<'a> {
+    pub fn new(actuator_type: &'a ActuatorType) -> Conversion<'a> {
+        Conversion { actuator_type }
+    }
+
+    /// Parses `s` into a candidate state for this type, then runs it
+    /// through the same bounds check `Actuator::valid_state` uses so the
+    /// two can't drift apart.
+    pub fn parse(&self, s: &str) -> Result<ActuatorState> {
+        let candidate = match self.actuator_type {
+            ActuatorType::Toggle => match s.to_lowercase().as_ref() {
+                "on" => ActuatorState::Toggle(true),
+                "off" => ActuatorState::Toggle(false),
+                _ => return Err(InvalidArgument(IAE::ActuatorState)),
+            },
+            ActuatorType::FloatValue { .. } => {
+                let value = f64::from_str(s).map_err(|_| InvalidArgument(IAE::ActuatorState))?;
+                ActuatorState::FloatValue(value)
+            },
+            ActuatorType::Enum { variants } => {
+                let variant = variants.iter().find(|v| v.as_str() == s)
+                    .ok_or(InvalidArgument(IAE::ActuatorState))?;
+                ActuatorState::Enum(variant.clone())
+            },
+            ActuatorType::Stepped { .. } => {
+                let value = f64::from_str(s).map_err(|_| InvalidArgument(IAE::ActuatorState))?;
+                ActuatorState::Stepped(value)
+            },
+        };
 
-    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
-        match s.to_lowercase().as_ref() {
-            "on" => Ok(ActuatorState::Toggle(true)),
-            "off" => Ok(ActuatorState::Toggle(false)),
-            _ => f64::from_str(s).map(|f| ActuatorState::FloatValue(f))
+        if is_valid_for_type(self.actuator_type, &candidate) {
+            Ok(candidate)
+        } else {
+            Err(InvalidArgument(IAE::ActuatorState))
         }
     }
 }
 
+/// Whether `value` falls within `[min, max]` a whole number of `step`s
+/// above `min` (within floating-point tolerance).
+fn on_step(value: f64, min: f64, max: f64, step: f64) -> bool {
+    if value < min || value > max {
+        return false;
+    }
+
+    let steps = (value - min) / step;
+    (steps - steps.round()).abs() < 1e-9
+}
+
+/// Whether `state` is a value `actuator_type` can actually hold: the
+/// right variant, and (for `FloatValue`/`Stepped`) within the declared
+/// bounds, (for `Stepped`) on a step boundary, and (for `Enum`) one of
+/// the declared variants. Shared by `Conversion::parse` and `Actuator::valid_state` so
+/// a string-parsed state and a directly-constructed one are held to the
+/// same rule.
+fn is_valid_for_type(actuator_type: &ActuatorType, state: &ActuatorState) -> bool {
+    match actuator_type {
+        ActuatorType::Toggle => match state {
+            &ActuatorState::Toggle(_) => true,
+            _ => false,
+        },
+        ActuatorType::FloatValue { min, max } => match state {
+            &ActuatorState::FloatValue(value) => (*min <= value && value <= *max),
+            _ => false,
+        },
+        ActuatorType::Enum { variants } => match state {
+            ActuatorState::Enum(value) => variants.contains(value),
+            _ => false,
+        },
+        ActuatorType::Stepped { min, max, step } => match state {
+            &ActuatorState::Stepped(value) => on_step(value, *min, *max, *step),
+            _ => false,
+        },
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ActuatorInfo {
     pub name: String,
@@ -63,18 +150,22 @@ pub struct ActuatorInfo {
 
 impl ValidCheck for ActuatorInfo {
     fn valid(&self) -> bool {
-        match self.actuator_type {
+        match &self.actuator_type {
             ActuatorType::Toggle => true,
             ActuatorType::FloatValue { min, max } => min < max,
+            ActuatorType::Enum { variants } => !variants.is_empty(),
+            ActuatorType::Stepped { min, max, step } => min < max && *step > 0.0 && *step <= max - min,
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Actuator {
     pub info: ActuatorInfo,
 
     timeslots: BTreeMap<u32, TimeSlot>,
     default_state: ActuatorState,
+    overrun: BTreeMap<u32, OverrunBound>,
 
     next_timeslot_id: u32,
     // TODO: would be nice to be per-timeslot, but shouldn't be exposed via RPC either...
@@ -88,6 +179,7 @@ impl Actuator {
             info,
             timeslots: BTreeMap::new(),
             default_state,
+            overrun: BTreeMap::new(),
             next_timeslot_id: 0,
             next_override_id: 0,
         }));
@@ -143,6 +235,7 @@ impl Actuator {
 
     pub fn remove_time_slot(&mut self, time_slot_id: u32) -> Result<()> {
         if self.timeslots.remove(&time_slot_id).is_some() {
+            self.overrun.remove(&time_slot_id);
             Ok(())
         } else {
             Err(InvalidArgument(IAE::TimeSlotId))
@@ -269,17 +362,37 @@ impl Actuator {
         }
     }
 
-    fn valid_state(&self, state: &ActuatorState) -> bool {
-        match self.info.actuator_type {
-            ActuatorType::Toggle => match state {
-                &ActuatorState::Toggle(_) => true,
-                _ => false,
-            },
-            ActuatorType::FloatValue { min, max } => match state {
-                &ActuatorState::FloatValue(value) => (min <= value && value <= max),
-                _ => false
-            },
+    pub fn time_slot_set_overrun(&mut self, time_slot_id: u32,
+                                 overrun: OverrunBound) -> Result<()> {
+        if !self.timeslots.contains_key(&time_slot_id) {
+            return Err(InvalidArgument(IAE::TimeSlotId))
+        }
+
+        self.overrun.insert(time_slot_id, overrun);
+        Ok(())
+    }
+
+    pub fn time_slot_clear_overrun(&mut self, time_slot_id: u32) -> Result<()> {
+        if !self.timeslots.contains_key(&time_slot_id) {
+            return Err(InvalidArgument(IAE::TimeSlotId))
         }
+
+        self.overrun.remove(&time_slot_id);
+        Ok(())
+    }
+
+    pub fn overrun(&self, time_slot_id: u32) -> Option<&OverrunBound> {
+        self.overrun.get(&time_slot_id)
+    }
+
+    /// Parse `s` into an `ActuatorState` valid for this actuator's
+    /// declared type.
+    pub fn parse_state(&self, s: &str) -> Result<ActuatorState> {
+        Conversion::new(&self.info.actuator_type).parse(s)
+    }
+
+    fn valid_state(&self, state: &ActuatorState) -> bool {
+        is_valid_for_type(&self.info.actuator_type, state)
     }
 }
 
@@ -288,3 +401,90 @@ impl ValidCheck for Actuator {
         self.info.valid() && self.valid_state(&self.default_state)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_step_accepts_exact_multiples_within_bounds() {
+        assert!(on_step(4.5, 0.0, 10.0, 1.5));
+    }
+
+    #[test]
+    fn on_step_tolerates_float_rounding_error() {
+        assert!(on_step(0.1 + 0.2, 0.0, 10.0, 0.1));
+    }
+
+    #[test]
+    fn on_step_rejects_off_grid_values() {
+        assert!(!on_step(4.6, 0.0, 10.0, 1.5));
+    }
+
+    #[test]
+    fn on_step_rejects_out_of_bounds_values() {
+        assert!(!on_step(-1.5, 0.0, 10.0, 1.5));
+        assert!(!on_step(11.5, 0.0, 10.0, 1.5));
+    }
+
+    #[test]
+    fn is_valid_for_type_rejects_mismatched_variant() {
+        let toggle_type = ActuatorType::Toggle;
+        assert!(!is_valid_for_type(&toggle_type, &ActuatorState::FloatValue(1.0)));
+    }
+
+    #[test]
+    fn is_valid_for_type_checks_float_bounds() {
+        let float_type = ActuatorType::FloatValue { min: 0.0, max: 10.0 };
+        assert!(is_valid_for_type(&float_type, &ActuatorState::FloatValue(5.0)));
+        assert!(!is_valid_for_type(&float_type, &ActuatorState::FloatValue(10.1)));
+    }
+
+    #[test]
+    fn is_valid_for_type_checks_enum_variant() {
+        let enum_type = ActuatorType::Enum { variants: vec!["heat".to_string(), "cool".to_string()] };
+        assert!(is_valid_for_type(&enum_type, &ActuatorState::Enum("heat".to_string())));
+        assert!(!is_valid_for_type(&enum_type, &ActuatorState::Enum("fan".to_string())));
+    }
+
+    #[test]
+    fn is_valid_for_type_checks_step_alignment() {
+        let stepped_type = ActuatorType::Stepped { min: 0.0, max: 3.0, step: 1.0 };
+        assert!(is_valid_for_type(&stepped_type, &ActuatorState::Stepped(2.0)));
+        assert!(!is_valid_for_type(&stepped_type, &ActuatorState::Stepped(2.5)));
+    }
+
+    #[test]
+    fn conversion_parse_toggle_accepts_on_off_case_insensitively() {
+        let toggle_type = ActuatorType::Toggle;
+        let conversion = Conversion::new(&toggle_type);
+
+        assert_eq!(conversion.parse("On").unwrap(), ActuatorState::Toggle(true));
+        assert_eq!(conversion.parse("OFF").unwrap(), ActuatorState::Toggle(false));
+        assert!(conversion.parse("1").is_err());
+    }
+
+    #[test]
+    fn conversion_parse_rejects_enum_variant_outside_declared_set() {
+        let enum_type = ActuatorType::Enum { variants: vec!["heat".to_string(), "cool".to_string()] };
+        let conversion = Conversion::new(&enum_type);
+
+        assert_eq!(conversion.parse("cool").unwrap(), ActuatorState::Enum("cool".to_string()));
+        assert!(conversion.parse("fan").is_err());
+    }
+
+    #[test]
+    fn conversion_parse_stepped_rejects_off_step_values() {
+        let stepped_type = ActuatorType::Stepped { min: 0.0, max: 3.0, step: 1.0 };
+        let conversion = Conversion::new(&stepped_type);
+
+        assert_eq!(conversion.parse("2").unwrap(), ActuatorState::Stepped(2.0));
+        assert!(conversion.parse("2.5").is_err());
+    }
+
+    #[test]
+    fn conversion_parse_stepped_rejects_out_of_bounds_values() {
+        let stepped_type = ActuatorType::Stepped { min: 0.0, max: 3.0, step: 1.0 };
+        assert!(Conversion::new(&stepped_type).parse("4").is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use actuator::ActuatorState;
+use schedule::Schedule;
+use sensor;
+use server::Server;
+use time::{Date, Time};
+
+/// Width of a single wheel slot.
+pub const NS_PER_SLOT: u64 = 1_000_000_000;
+/// Number of slots in the wheel; together with `NS_PER_SLOT` this bounds
+/// how far ahead a timer can sit before it has to live on the overflow
+/// list instead.
+pub const SLOTS: usize = 64;
+
+const WHEEL_WINDOW_NS: u64 = SLOTS as u64 * NS_PER_SLOT;
+const NS_PER_DAY: u64 = 86_400_000_000_000;
+
+/// Something that can be driven to a new `ActuatorState` — a GPIO pin, a
+/// remote device, whatever the actuator actually is in the physical
+/// world. Registered per-actuator on the `Engine`.
+pub trait ActuatorBackend: Send + Sync {
+    fn apply(&self, state: &ActuatorState);
+}
+
+struct Timer {
+    actuator_id: u32,
+    due_ns: u64,
+}
+
+/// A hashed timing wheel: `SLOTS` buckets of `NS_PER_SLOT` width each,
+/// plus an overflow list for timers further out than the wheel's window
+/// (`SLOTS * NS_PER_SLOT`).
+struct TimerWheel {
+    epoch: Instant,
+    elapsed_ns: u64,
+    cursor: usize,
+    slots: Vec<Vec<Timer>>,
+    overflow: Vec<Timer>,
+}
+
+impl TimerWheel {
+    fn new() -> TimerWheel {
+        TimerWheel {
+            epoch: Instant::now(),
+            elapsed_ns: 0,
+            cursor: 0,
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            overflow: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, timer: Timer) {
+        if timer.due_ns < self.elapsed_ns + WHEEL_WINDOW_NS {
+            let slot = ((timer.due_ns / NS_PER_SLOT) as usize) % SLOTS;
+            self.slots[slot].push(timer);
+        } else {
+            self.overflow.push(timer);
+        }
+    }
+
+    fn schedule_at(&mut self, actuator_id: u32, due_ns: u64) {
+        self.insert(Timer { actuator_id, due_ns });
+    }
+
+    /// Advance as far as real elapsed time since `epoch` allows, firing
+    /// every timer whose slot has now fully elapsed and re-inserting any
+    /// overflow timers that fall inside the wheel's window as a result.
+    /// Catches up in one call if the caller is late, rather than drifting
+    /// out of sync with wall-clock time.
+    fn advance(&mut self) -> Vec<u32> {
+        let elapsed = self.epoch.elapsed();
+        let target_ns = elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos());
+        self.advance_to(target_ns)
+    }
+
+    fn advance_to(&mut self, target_ns: u64) -> Vec<u32> {
+        let mut fired = Vec::new();
+
+        while self.elapsed_ns + NS_PER_SLOT <= target_ns {
+            fired.extend(self.slots[self.cursor].drain(..).map(|t| t.actuator_id));
+
+            self.elapsed_ns += NS_PER_SLOT;
+            self.cursor = (self.cursor + 1) % SLOTS;
+
+            let window = self.elapsed_ns + WHEEL_WINDOW_NS;
+            let (ready, still_over): (Vec<Timer>, Vec<Timer>) =
+                self.overflow.drain(..).partition(|t| t.due_ns < window);
+            self.overflow = still_over;
+            for timer in ready {
+                let slot = ((timer.due_ns / NS_PER_SLOT) as usize) % SLOTS;
+                self.slots[slot].push(timer);
+            }
+        }
+
+        fired
+    }
+
+    fn cancel(&mut self, actuator_id: u32) {
+        for slot in self.slots.iter_mut() {
+            slot.retain(|t| t.actuator_id != actuator_id);
+        }
+        self.overflow.retain(|t| t.actuator_id != actuator_id);
+    }
+}
+
+/// Drives registered `ActuatorBackend`s at the wall-clock instants their
+/// schedules say they should change state. Sits alongside `Server` /
+/// `RpcServer`: it reads the same actuators but owns no RPC surface of
+/// its own, and it's the caller's job to invoke `tick` roughly once per
+/// `NS_PER_SLOT`.
+pub struct Engine {
+    server: Arc<RwLock<Server>>,
+    backends: HashMap<u32, Box<ActuatorBackend>>,
+    wheel: TimerWheel,
+}
+
+impl Engine {
+    pub fn new(server: Arc<RwLock<Server>>) -> Engine {
+        Engine {
+            server,
+            backends: HashMap::new(),
+            wheel: TimerWheel::new(),
+        }
+    }
+
+    /// Register a backend for `actuator_id` and queue its first
+    /// transition.
+    pub fn register_backend(&mut self, actuator_id: u32, backend: Box<ActuatorBackend>) {
+        self.backends.insert(actuator_id, backend);
+        self.requeue(actuator_id);
+    }
+
+    pub fn unregister_backend(&mut self, actuator_id: u32) {
+        self.backends.remove(&actuator_id);
+        self.wheel.cancel(actuator_id);
+    }
+
+    /// Advance the wheel to match elapsed wall-clock time, firing any
+    /// timers that are now due.
+    pub fn tick(&mut self) {
+        for actuator_id in self.wheel.advance() {
+            self.fire(actuator_id);
+        }
+    }
+
+    fn fire(&mut self, actuator_id: u32) {
+        if let Some(state) = self.current_state(actuator_id) {
+            if let Some(backend) = self.backends.get(&actuator_id) {
+                backend.apply(&state);
+            }
+        }
+        self.requeue(actuator_id);
+    }
+
+    fn current_state(&self, actuator_id: u32) -> Option<ActuatorState> {
+        let server = self.server.read().unwrap();
+        let actuator = server.list_actuators().get(&actuator_id)?.clone();
+
+        let today = Date::today();
+        let now = Time::now();
+        // Start the window a day early so `effective_state` can see
+        // yesterday's slots too — an overrun extension can carry one
+        // past midnight.
+        let mut yesterday = today.clone();
+        yesterday -= 1;
+        let schedule = Schedule::compute(actuator.timeslots(), &yesterday, 2);
+
+        Some(sensor::effective_state(&actuator, &schedule, server.sensors(), &today, &now))
+    }
+
+    /// Compute and enqueue this actuator's next transition, dropping it
+    /// silently if it no longer exists or nothing changes within the
+    /// window `Schedule::compute` looked at. Cancels any timer already in
+    /// flight for this actuator first, so re-registering a backend or
+    /// firing a timer never leaves duplicates behind.
+    fn requeue(&mut self, actuator_id: u32) {
+        self.wheel.cancel(actuator_id);
+
+        let due_in = {
+            let server = self.server.read().unwrap();
+            let actuator = match server.list_actuators().get(&actuator_id) {
+                Some(a) => a.clone(),
+                None => return,
+            };
+
+            let today = Date::today();
+            let now = Time::now();
+            let mut yesterday = today.clone();
+            yesterday -= 1;
+            // `yesterday` is day offset 0 in this window, `today` is 1 —
+            // needed so `in_overrun_window`/`effective_state` can see a
+            // slot that ended yesterday but is still within `max_extra`.
+            let schedule = Schedule::compute(actuator.timeslots(), &yesterday, 3);
+
+            if sensor::in_overrun_window(&actuator, &schedule, &today, &now) {
+                // A sensor reading can flip at any time, so while we might
+                // still be in an overrun extension, re-check on the next
+                // tick rather than waiting for the nominal schedule boundary.
+                Duration::from_nanos(NS_PER_SLOT)
+            } else {
+                match schedule.next_transition(1, &now, actuator.default_state()) {
+                    Some((day, time, _)) => duration_until(&now, day - 1, &time),
+                    None => return,
+                }
+            }
+        };
+
+        let due_ns = self.wheel.elapsed_ns + due_in.as_secs() * 1_000_000_000
+            + u64::from(due_in.subsec_nanos());
+        self.wheel.schedule_at(actuator_id, due_ns);
+    }
+}
+
+/// Duration from `from_time` (today) until `to_day` days from today, at
+/// `to_time`.
+fn duration_until(from_time: &Time, to_day: u32, to_time: &Time) -> Duration {
+    let mut ns = u64::from(to_day) * NS_PER_DAY;
+    ns += to_time.since_midnight().as_nanos() as u64;
+    ns -= from_time.since_midnight().as_nanos() as u64;
+    Duration::from_nanos(ns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wheel_with(timers: &[(u32, u64)]) -> TimerWheel {
+        let mut wheel = TimerWheel::new();
+        for &(actuator_id, due_ns) in timers {
+            wheel.schedule_at(actuator_id, due_ns);
+        }
+        wheel
+    }
+
+    #[test]
+    fn does_not_fire_before_due() {
+        let mut wheel = wheel_with(&[(1, NS_PER_SLOT * 3)]);
+        assert_eq!(wheel.advance_to(NS_PER_SLOT * 2), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn fires_timer_once_its_slot_has_fully_elapsed() {
+        let mut wheel = wheel_with(&[(1, NS_PER_SLOT * 2)]);
+        assert_eq!(wheel.advance_to(NS_PER_SLOT * 3), vec![1]);
+    }
+
+    #[test]
+    fn overflow_timer_migrates_into_window_and_fires() {
+        let due_ns = WHEEL_WINDOW_NS + NS_PER_SLOT * 2;
+        let mut wheel = wheel_with(&[(1, due_ns)]);
+        assert!(wheel.overflow.iter().any(|t| t.actuator_id == 1));
+
+        assert_eq!(wheel.advance_to(due_ns + NS_PER_SLOT), vec![1]);
+        assert!(wheel.overflow.is_empty());
+    }
+
+    #[test]
+    fn catches_up_multiple_slots_in_one_advance() {
+        let mut wheel = wheel_with(&[(1, NS_PER_SLOT), (2, NS_PER_SLOT * 5)]);
+        let mut fired = wheel.advance_to(NS_PER_SLOT * 10);
+        fired.sort();
+        assert_eq!(fired, vec![1, 2]);
+    }
+
+    #[test]
+    fn cancel_removes_timer_from_slot_and_overflow() {
+        let mut wheel = wheel_with(&[(1, NS_PER_SLOT * 2), (1, WHEEL_WINDOW_NS + NS_PER_SLOT)]);
+        wheel.cancel(1);
+        assert_eq!(wheel.advance_to(WHEEL_WINDOW_NS * 2), Vec::<u32>::new());
+    }
+}
@@ -1,21 +1,52 @@
 use std::collections::HashMap;
+use std::result;
 use std::sync::{Arc,RwLock};
 
 use server::*;
+use session::{Capability, SessionHandle};
+
+/// Why an argument to an RPC was rejected, independent of which RPC it
+/// was — `Error::InvalidArgument` carries one of these to say which id
+/// or value didn't check out.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum InvalArgError {
+    ActuatorId,
+    ActuatorState,
+    TimePeriod,
+    TimeSlotId,
+    TimeOverrideId,
+    SensorId,
+}
+
+/// Error surface for every mutating RPC.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Error {
+    InvalidArgument(InvalArgError),
+    TimeSlotOverlap(u32),
+    TimeOverrideOverlap(u32),
+    Forbidden(Capability),
+}
+
+pub type Result<T> = result::Result<T, Error>;
 
 service! {
-    rpc list_actuators() -> HashMap<u32, Actuator>;
-    rpc get_schedule(actuator_id: u32) -> Schedule | Error;
-
-    rpc set_default_state(actuator_id: u32, default_state: ActuatorState) -> () | Error;
-
-    rpc add_time_slot(actuator_id: u32, time_period: TimePeriod, actuator_state: ActuatorState, enabled: bool) -> u32 | Error;
-    rpc remove_time_slot(actuator_id: u32, time_slot_id: u32) -> () | Error;
-    rpc time_slot_set_time_period(actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> () | Error;
-    rpc time_slot_set_enabled(actuator_id: u32, time_slot_id: u32, enabled: bool) -> () | Error;
-    rpc time_slot_set_actuator_state(actuator_id: u32, time_slot_id: u32, actuator_state: ActuatorState) -> () | Error;
-    rpc time_slot_add_time_override(actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> u32 | Error;
-    rpc time_slot_remove_time_override(actuator_id: u32, time_slot_id: u32, time_override_id: u32) -> () | Error;
+    rpc list_actuators(session: SessionHandle) -> HashMap<u32, Actuator>;
+    rpc get_schedule(session: SessionHandle, actuator_id: u32) -> Schedule | Error;
+
+    rpc set_default_state(session: SessionHandle, actuator_id: u32, default_state: ActuatorState) -> () | Error;
+
+    rpc add_time_slot(session: SessionHandle, actuator_id: u32, time_period: TimePeriod, actuator_state: ActuatorState, enabled: bool) -> u32 | Error;
+    rpc remove_time_slot(session: SessionHandle, actuator_id: u32, time_slot_id: u32) -> () | Error;
+    rpc time_slot_set_time_period(session: SessionHandle, actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> () | Error;
+    rpc time_slot_set_enabled(session: SessionHandle, actuator_id: u32, time_slot_id: u32, enabled: bool) -> () | Error;
+    rpc time_slot_set_actuator_state(session: SessionHandle, actuator_id: u32, time_slot_id: u32, actuator_state: ActuatorState) -> () | Error;
+    rpc time_slot_add_time_override(session: SessionHandle, actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> u32 | Error;
+    rpc time_slot_remove_time_override(session: SessionHandle, actuator_id: u32, time_slot_id: u32, time_override_id: u32) -> () | Error;
+
+    rpc register_sensor(session: SessionHandle, name: String) -> SensorId | Error;
+    rpc push_sensor_reading(session: SessionHandle, sensor_id: SensorId, value: f64) -> () | Error;
+    rpc time_slot_set_overrun(session: SessionHandle, actuator_id: u32, time_slot_id: u32, overrun: OverrunBound) -> () | Error;
+    rpc time_slot_clear_overrun(session: SessionHandle, actuator_id: u32, time_slot_id: u32) -> () | Error;
 }
 
 #[derive(Clone)]
@@ -34,45 +65,176 @@ impl RpcServer {
 type ResultNever<T> = ::std::result::Result<T, ::tarpc::util::Never>;
 
 impl SyncService for RpcServer {
-    fn list_actuators(&self) -> ResultNever<HashMap<u32, Actuator>> {
-        Ok(self.server.read().unwrap().list_actuators().clone())
+    fn list_actuators(&self, session: SessionHandle) -> ResultNever<HashMap<u32, Actuator>> {
+        let actuators = self.server.read().unwrap().list_actuators().clone();
+        Ok(actuators.into_iter().filter(|&(id, _)| session.can(id, Capability::Read)).collect())
     }
 
-    fn get_schedule(&self, actuator_id: u32) -> Result<Schedule> {
+    fn get_schedule(&self, session: SessionHandle, actuator_id: u32) -> Result<Schedule> {
+        if !session.can(actuator_id, Capability::Read) {
+            return Err(Error::Forbidden(Capability::Read))
+        }
+
         self.server.read().unwrap().get_schedule(actuator_id).map(|s| s.clone())
     }
 
-    fn set_default_state(&self, actuator_id: u32, default_state: ActuatorState) -> Result<()> {
+    fn set_default_state(&self, session: SessionHandle, actuator_id: u32, default_state: ActuatorState) -> Result<()> {
+        if !session.can(actuator_id, Capability::Admin) {
+            return Err(Error::Forbidden(Capability::Admin))
+        }
+
         self.server.write().unwrap().set_default_state(actuator_id, default_state)
     }
 
-    fn add_time_slot(&self, actuator_id: u32, time_period: TimePeriod, actuator_state: ActuatorState, enabled: bool) -> Result<u32> {
+    fn add_time_slot(&self, session: SessionHandle, actuator_id: u32, time_period: TimePeriod, actuator_state: ActuatorState, enabled: bool) -> Result<u32> {
+        if !session.can(actuator_id, Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
         self.server.write().unwrap().add_time_slot(actuator_id, time_period, actuator_state, enabled)
     }
 
-    fn remove_time_slot(&self, actuator_id: u32, time_slot_id: u32) -> Result<()> {
+    fn remove_time_slot(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32) -> Result<()> {
+        if !session.can(actuator_id, Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
         self.server.write().unwrap().remove_time_slot(actuator_id, time_slot_id)
     }
 
-    fn time_slot_set_time_period(&self, actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> Result<()> {
+    fn time_slot_set_time_period(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> Result<()> {
+        if !session.can(actuator_id, Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
         self.server.write().unwrap().time_slot_set_time_period(actuator_id, time_slot_id, time_period)
     }
 
-    fn time_slot_set_enabled(&self, actuator_id: u32, time_slot_id: u32, enabled: bool) -> Result<()> {
+    fn time_slot_set_enabled(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32, enabled: bool) -> Result<()> {
+        if !session.can(actuator_id, Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
         self.server.write().unwrap().time_slot_set_enabled(actuator_id, time_slot_id, enabled)
     }
 
-    fn time_slot_set_actuator_state(&self, actuator_id: u32, time_slot_id: u32, actuator_state: ActuatorState) -> Result<()> {
+    fn time_slot_set_actuator_state(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32, actuator_state: ActuatorState) -> Result<()> {
+        if !session.can(actuator_id, Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
         self.server.write().unwrap().time_slot_set_actuator_state(actuator_id, time_slot_id, actuator_state)
     }
 
-    fn time_slot_add_time_override(&self, actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> Result<u32> {
+    fn time_slot_add_time_override(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32, time_period: TimePeriod) -> Result<u32> {
+        if !session.can(actuator_id, Capability::Use) {
+            return Err(Error::Forbidden(Capability::Use))
+        }
+
         self.server.write().unwrap().time_slot_add_time_override(actuator_id, time_slot_id, time_period)
     }
 
-    fn time_slot_remove_time_override(&self, actuator_id: u32, time_slot_id: u32, time_override_id: u32) -> Result<()> {
+    fn time_slot_remove_time_override(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32, time_override_id: u32) -> Result<()> {
+        if !session.can(actuator_id, Capability::Use) {
+            return Err(Error::Forbidden(Capability::Use))
+        }
+
         self.server.write().unwrap().time_slot_remove_time_override(actuator_id, time_slot_id, time_override_id)
     }
+
+    fn register_sensor(&self, session: SessionHandle, name: String) -> Result<SensorId> {
+        if !session.can_any(Capability::Admin) {
+            return Err(Error::Forbidden(Capability::Admin))
+        }
+
+        Ok(self.server.write().unwrap().register_sensor(name))
+    }
+
+    fn push_sensor_reading(&self, session: SessionHandle, sensor_id: SensorId, value: f64) -> Result<()> {
+        if !session.can_any(Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
+        self.server.write().unwrap().push_sensor_reading(sensor_id, value)
+    }
+
+    fn time_slot_set_overrun(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32, overrun: OverrunBound) -> Result<()> {
+        if !session.can(actuator_id, Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
+        self.server.write().unwrap().time_slot_set_overrun(actuator_id, time_slot_id, overrun)
+    }
+
+    fn time_slot_clear_overrun(&self, session: SessionHandle, actuator_id: u32, time_slot_id: u32) -> Result<()> {
+        if !session.can(actuator_id, Capability::Manage) {
+            return Err(Error::Forbidden(Capability::Manage))
+        }
+
+        self.server.write().unwrap().time_slot_clear_overrun(actuator_id, time_slot_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_with(grants: &[(u32, Capability)]) -> SessionHandle {
+        let mut session = SessionHandle::new("test".to_string());
+        for &(actuator_id, capability) in grants {
+            session.grant(actuator_id, capability);
+        }
+        session
+    }
+
+    fn add_toggle_actuator(rpc: &RpcServer, name: &str) -> u32 {
+        let info = ActuatorInfo { name: name.to_string(), actuator_type: ActuatorType::Toggle };
+        rpc.server.write().unwrap().add_actuator(info, ActuatorState::Toggle(false))
+    }
+
+    #[test]
+    fn list_actuators_filters_out_actuators_without_read() {
+        let rpc = RpcServer::new();
+        let a = add_toggle_actuator(&rpc, "a");
+        let b = add_toggle_actuator(&rpc, "b");
+
+        let visible = rpc.list_actuators(session_with(&[(a, Capability::Read)])).unwrap();
+
+        assert!(visible.contains_key(&a));
+        assert!(!visible.contains_key(&b));
+    }
+
+    #[test]
+    fn set_default_state_is_forbidden_without_admin() {
+        let rpc = RpcServer::new();
+        let a = add_toggle_actuator(&rpc, "a");
+
+        let manager = session_with(&[(a, Capability::Manage)]);
+        let result = rpc.set_default_state(manager, a, ActuatorState::Toggle(true));
+
+        match result {
+            Err(Error::Forbidden(Capability::Admin)) => {},
+            other => panic!("expected Forbidden(Admin), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_default_state_is_allowed_with_admin() {
+        let rpc = RpcServer::new();
+        let a = add_toggle_actuator(&rpc, "a");
+
+        let admin = session_with(&[(a, Capability::Admin)]);
+        assert!(rpc.set_default_state(admin, a, ActuatorState::Toggle(true)).is_ok());
+    }
+
+    #[test]
+    fn register_sensor_requires_admin_on_at_least_one_actuator() {
+        let rpc = RpcServer::new();
+        let a = add_toggle_actuator(&rpc, "a");
+
+        assert!(rpc.register_sensor(session_with(&[(a, Capability::Manage)]), "thermostat".to_string()).is_err());
+        assert!(rpc.register_sensor(session_with(&[(a, Capability::Admin)]), "thermostat".to_string()).is_ok());
+    }
 }
 
 /* impl FutureService for RpcServer {
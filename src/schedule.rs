@@ -4,6 +4,7 @@ use actuator::ActuatorState;
 use time::*;
 use time_slot::*;
 
+#[derive(Clone)]
 pub struct ScheduleSlot {
     pub time_interval: TimeInterval,
     pub actuator_state: ActuatorState,
@@ -11,6 +12,7 @@ pub struct ScheduleSlot {
     pub override_id: Option<u32>,
 }
 
+#[derive(Clone)]
 pub struct Schedule {
     pub days: BTreeMap<Date, Vec<ScheduleSlot>>,
 }
@@ -46,4 +48,26 @@ impl Schedule {
              days
         }
     }
+
+    /// Find the first slot boundary (a slot start or a slot end) strictly
+    /// after `(from_day, from_time)`, where `from_day` is an offset into
+    /// `self.days` (0 = its first day). Returns the day offset, the time of
+    /// day it falls on, and the slot's state for a start or `default_state`
+    /// for an end. Where one slot ends at the same instant another starts,
+    /// which of the two boundaries is returned is not defined.
+    pub fn next_transition(&self, from_day: u32, from_time: &Time,
+                           default_state: &ActuatorState) -> Option<(u32, Time, ActuatorState)> {
+        let mut boundaries = Vec::new();
+
+        for (day, slots) in self.days.values().enumerate() {
+            let day = day as u32;
+            for s in slots {
+                boundaries.push((day, s.time_interval.start, s.actuator_state.clone()));
+                boundaries.push((day, s.time_interval.end, default_state.clone()));
+            }
+        }
+
+        boundaries.sort_unstable_by_key(|&(day, time, _)| (day, time));
+        boundaries.into_iter().find(|&(day, time, _)| (day, time) > (from_day, *from_time))
+    }
 }
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::result;
+use std::time::Duration;
+
+use actuator::Actuator;
+use rpc::InvalArgError as IAE;
+use rpc::Error::*;
+use schedule::Schedule;
+use time::{Date, Time};
+
+pub type Result<T> = result::Result<T, ::rpc::Error>;
+
+pub type SensorId = u32;
+
+/// A single scalar input (a thermostat, a moisture probe, ...) that slots
+/// can consult via an `OverrunBound` to decide when to switch off.
+pub struct Sensor {
+    pub name: String,
+    reading: Option<f64>,
+}
+
+impl Sensor {
+    pub fn new(name: String) -> Sensor {
+        Sensor { name, reading: None }
+    }
+
+    pub fn reading(&self) -> Option<f64> {
+        self.reading
+    }
+
+    pub fn push_reading(&mut self, value: f64) {
+        self.reading = Some(value);
+    }
+}
+
+pub struct SensorRegistry {
+    sensors: HashMap<SensorId, Sensor>,
+    next_sensor_id: SensorId,
+}
+
+impl SensorRegistry {
+    pub fn new() -> SensorRegistry {
+        SensorRegistry {
+            sensors: HashMap::new(),
+            next_sensor_id: 0,
+        }
+    }
+
+    pub fn register(&mut self, name: String) -> SensorId {
+        let id = self.next_sensor_id;
+        self.sensors.insert(id, Sensor::new(name));
+        self.next_sensor_id += 1;
+        id
+    }
+
+    pub fn push_reading(&mut self, sensor_id: SensorId, value: f64) -> Result<()> {
+        self.sensors.get_mut(&sensor_id)
+            .map(|s| s.push_reading(value))
+            .ok_or(InvalidArgument(IAE::SensorId))
+    }
+
+    pub fn reading(&self, sensor_id: SensorId) -> Option<f64> {
+        self.sensors.get(&sensor_id).and_then(|s| s.reading())
+    }
+}
+
+/// Lets a `TimeSlot` stay active past its scheduled end until a sensor
+/// target is met: while the slot is active, or within `max_extra` after
+/// its scheduled end, and `sensor_id`'s reading is still below
+/// `target_value`, the actuator keeps the slot's state instead of
+/// reverting to the actuator's default.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OverrunBound {
+    pub sensor_id: SensorId,
+    pub target_value: f64,
+    pub max_extra: Duration,
+}
+
+/// Time elapsed since a slot's scheduled end, both given as offsets from
+/// midnight. `crossed_midnight` must be set when the slot ended on the
+/// day before `now`'s, so the elapsed time includes the rollover instead
+/// of reading as negative (or as a bogus near-full-day gap).
+fn extra_since(now_since_midnight: Duration, end_since_midnight: Duration, crossed_midnight: bool) -> Duration {
+    let day = if crossed_midnight { Duration::from_secs(86_400) } else { Duration::from_secs(0) };
+    day + now_since_midnight - end_since_midnight
+}
+
+/// Whether an `OverrunBound` is still keeping a slot active: within
+/// `max_extra` of its scheduled end, and the sensor hasn't reached
+/// `target_value` yet (a sensor with no reading yet is treated as "at
+/// target", i.e. not overrunning).
+fn overrun_active(reading: Option<f64>, target_value: f64, max_extra: Duration, extra: Duration) -> bool {
+    extra <= max_extra && reading.unwrap_or(target_value) < target_value
+}
+
+fn overrun_state(actuator: &Actuator, sensors: &SensorRegistry, s: &::schedule::ScheduleSlot,
+                 extra: Duration) -> Option<::actuator::ActuatorState> {
+    let overrun = actuator.overrun(s.timeslot_id)?;
+    let reading = sensors.reading(overrun.sensor_id);
+
+    if overrun_active(reading, overrun.target_value, overrun.max_extra, extra) {
+        Some(s.actuator_state.clone())
+    } else {
+        None
+    }
+}
+
+/// The state an actuator should be in right now, consulting `sensors` for
+/// any slot that carries an `OverrunBound`. Also considers the previous
+/// day's slots, since an overrun extension can carry a slot past
+/// midnight.
+///
+/// A directly-active slot always wins: slots are only required not to
+/// overlap on their raw `TimePeriod`, so an overrunning slot can still
+/// legally end right where another slot's own window begins, and that
+/// slot's schedule must not be shadowed by the earlier slot's overrun.
+pub fn effective_state(actuator: &Actuator, schedule: &Schedule, sensors: &SensorRegistry,
+                       day: &Date, now: &Time) -> ::actuator::ActuatorState {
+    if let Some(slots) = schedule.days.get(day) {
+        if let Some(s) = slots.iter().find(|s| s.time_interval.start <= *now && *now < s.time_interval.end) {
+            return s.actuator_state.clone();
+        }
+
+        for s in slots {
+            if *now >= s.time_interval.end {
+                let extra = extra_since(now.since_midnight(), s.time_interval.end.since_midnight(), false);
+                if let Some(state) = overrun_state(actuator, sensors, s, extra) {
+                    return state;
+                }
+            }
+        }
+    }
+
+    let mut yesterday = day.clone();
+    yesterday -= 1;
+    if let Some(slots) = schedule.days.get(&yesterday) {
+        for s in slots {
+            let extra = extra_since(now.since_midnight(), s.time_interval.end.since_midnight(), true);
+            if let Some(state) = overrun_state(actuator, sensors, s, extra) {
+                return state;
+            }
+        }
+    }
+
+    actuator.default_state().clone()
+}
+
+/// Whether any of `actuator`'s slots — today's or yesterday's, to cover a
+/// midnight rollover — might still be within an overrun extension right
+/// now, irrespective of the latest sensor reading. Used to decide whether
+/// to poll for state changes more eagerly than the nominal schedule
+/// boundaries would require.
+pub fn in_overrun_window(actuator: &Actuator, schedule: &Schedule, day: &Date, now: &Time) -> bool {
+    let mut yesterday = day.clone();
+    yesterday -= 1;
+
+    let today_hit = schedule.days.get(day).map_or(false, |slots| slots.iter().any(|s| {
+        *now >= s.time_interval.end
+            && actuator.overrun(s.timeslot_id).map_or(false, |o| {
+                extra_since(now.since_midnight(), s.time_interval.end.since_midnight(), false) <= o.max_extra
+            })
+    }));
+
+    if today_hit {
+        return true;
+    }
+
+    schedule.days.get(&yesterday).map_or(false, |slots| slots.iter().any(|s| {
+        actuator.overrun(s.timeslot_id).map_or(false, |o| {
+            extra_since(now.since_midnight(), s.time_interval.end.since_midnight(), true) <= o.max_extra
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrun_active_below_target_within_window() {
+        assert!(overrun_active(Some(18.0), 20.0, Duration::from_secs(600), Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn overrun_inactive_once_target_reached() {
+        assert!(!overrun_active(Some(20.0), 20.0, Duration::from_secs(600), Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn overrun_inactive_once_max_extra_elapsed() {
+        assert!(!overrun_active(Some(10.0), 20.0, Duration::from_secs(600), Duration::from_secs(601)));
+    }
+
+    #[test]
+    fn missing_reading_falls_back_to_target_and_is_not_overrun() {
+        assert!(!overrun_active(None, 20.0, Duration::from_secs(600), Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn extra_since_adds_a_day_across_midnight() {
+        // A slot ending at 23:50 with `now` at 00:05 the next day should
+        // read as 15 minutes of overrun, not a negative or huge duration.
+        let end = Duration::from_secs(23 * 3600 + 50 * 60);
+        let now = Duration::from_secs(5 * 60);
+        assert_eq!(extra_since(now, end, true), Duration::from_secs(15 * 60));
+    }
+
+    #[test]
+    fn extra_since_same_day() {
+        let end = Duration::from_secs(3600);
+        let now = Duration::from_secs(3700);
+        assert_eq!(extra_since(now, end, false), Duration::from_secs(100));
+    }
+}
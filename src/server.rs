@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+pub use actuator::{Actuator, ActuatorHandle, ActuatorInfo, ActuatorState, ActuatorType};
+pub use schedule::Schedule;
+pub use sensor::{OverrunBound, SensorId};
+pub use time_slot::TimePeriod;
+
+use rpc::Error;
+use rpc::InvalArgError as IAE;
+use rpc::Result;
+use sensor::SensorRegistry;
+
+/// Owns every actuator and the sensor registry they can be wired to via
+/// `OverrunBound`s. One instance backs an `RpcServer`; `Engine` holds it
+/// behind the same `Arc<RwLock<_>>` so it can read schedules and sensor
+/// readings without going through the RPC surface.
+pub struct Server {
+    actuators: HashMap<u32, ActuatorHandle>,
+    sensors: SensorRegistry,
+    next_actuator_id: u32,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            actuators: HashMap::new(),
+            sensors: SensorRegistry::new(),
+            next_actuator_id: 0,
+        }
+    }
+
+    /// Register a new actuator and return its id. Not exposed over RPC
+    /// yet — actuators are provisioned out of band for now.
+    pub fn add_actuator(&mut self, info: ActuatorInfo, default_state: ActuatorState) -> u32 {
+        let id = self.next_actuator_id;
+        self.actuators.insert(id, Actuator::new(info, default_state));
+        self.next_actuator_id += 1;
+        id
+    }
+
+    fn actuator(&self, actuator_id: u32) -> Result<ActuatorHandle> {
+        self.actuators.get(&actuator_id).cloned().ok_or(Error::InvalidArgument(IAE::ActuatorId))
+    }
+
+    pub fn list_actuators(&self) -> HashMap<u32, Actuator> {
+        self.actuators.iter()
+            .map(|(&id, handle)| (id, handle.read().unwrap().clone()))
+            .collect()
+    }
+
+    pub fn get_schedule(&self, actuator_id: u32) -> Result<Schedule> {
+        let actuator = self.actuator(actuator_id)?;
+        let actuator = actuator.read().unwrap();
+        Ok(Schedule::compute(actuator.timeslots(), &::time::Date::today(), 1))
+    }
+
+    pub fn set_default_state(&self, actuator_id: u32, default_state: ActuatorState) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().set_default_state(default_state)
+    }
+
+    pub fn add_time_slot(&self, actuator_id: u32, time_period: TimePeriod,
+                         actuator_state: ActuatorState, enabled: bool) -> Result<u32> {
+        self.actuator(actuator_id)?.write().unwrap().add_time_slot(time_period, actuator_state, enabled)
+    }
+
+    pub fn remove_time_slot(&self, actuator_id: u32, time_slot_id: u32) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().remove_time_slot(time_slot_id)
+    }
+
+    pub fn time_slot_set_time_period(&self, actuator_id: u32, time_slot_id: u32,
+                                     time_period: TimePeriod) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().time_slot_set_time_period(time_slot_id, time_period)
+    }
+
+    pub fn time_slot_set_enabled(&self, actuator_id: u32, time_slot_id: u32, enabled: bool) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().time_slot_set_enabled(time_slot_id, enabled)
+    }
+
+    pub fn time_slot_set_actuator_state(&self, actuator_id: u32, time_slot_id: u32,
+                                        actuator_state: ActuatorState) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().time_slot_set_actuator_state(time_slot_id, actuator_state)
+    }
+
+    pub fn time_slot_add_time_override(&self, actuator_id: u32, time_slot_id: u32,
+                                       time_period: TimePeriod) -> Result<u32> {
+        self.actuator(actuator_id)?.write().unwrap().time_slot_add_time_override(time_slot_id, time_period)
+    }
+
+    pub fn time_slot_remove_time_override(&self, actuator_id: u32, time_slot_id: u32,
+                                          time_override_id: u32) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().time_slot_remove_time_override(time_slot_id, time_override_id)
+    }
+
+    pub fn register_sensor(&mut self, name: String) -> SensorId {
+        self.sensors.register(name)
+    }
+
+    pub fn push_sensor_reading(&mut self, sensor_id: SensorId, value: f64) -> Result<()> {
+        self.sensors.push_reading(sensor_id, value)
+    }
+
+    pub fn sensors(&self) -> &SensorRegistry {
+        &self.sensors
+    }
+
+    pub fn time_slot_set_overrun(&self, actuator_id: u32, time_slot_id: u32, overrun: OverrunBound) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().time_slot_set_overrun(time_slot_id, overrun)
+    }
+
+    pub fn time_slot_clear_overrun(&self, actuator_id: u32, time_slot_id: u32) -> Result<()> {
+        self.actuator(actuator_id)?.write().unwrap().time_slot_clear_overrun(time_slot_id)
+    }
+}
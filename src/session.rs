@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+pub type Principal = String;
+
+/// Access tiers for a single actuator, from least to most privileged.
+/// Deriving `Ord` in this declaration order means a principal holding a
+/// higher tier is also entitled to everything a lower tier grants.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum Capability {
+    Read,
+    Use,
+    Manage,
+    Admin,
+}
+
+/// Identifies the caller of an RPC and the capabilities it holds, per
+/// actuator. Threaded through every RPC so `RpcServer` can gate mutating
+/// calls before they reach `Server`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionHandle {
+    pub principal: Principal,
+    roles: HashMap<u32, Capability>,
+}
+
+impl SessionHandle {
+    pub fn new(principal: Principal) -> SessionHandle {
+        SessionHandle {
+            principal,
+            roles: HashMap::new(),
+        }
+    }
+
+    pub fn grant(&mut self, actuator_id: u32, capability: Capability) {
+        self.roles.insert(actuator_id, capability);
+    }
+
+    pub fn revoke(&mut self, actuator_id: u32) {
+        self.roles.remove(&actuator_id);
+    }
+
+    pub fn can(&self, actuator_id: u32, required: Capability) -> bool {
+        self.roles.get(&actuator_id).map_or(false, |granted| *granted >= required)
+    }
+
+    /// Whether this session holds `required` on at least one actuator.
+    /// For RPCs that aren't scoped to a single actuator (e.g. registering
+    /// a sensor) but still shouldn't be callable by just anyone.
+    pub fn can_any(&self, required: Capability) -> bool {
+        self.roles.values().any(|granted| *granted >= required)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capability_ordering_is_least_to_most_privileged() {
+        assert!(Capability::Read < Capability::Use);
+        assert!(Capability::Use < Capability::Manage);
+        assert!(Capability::Manage < Capability::Admin);
+    }
+
+    #[test]
+    fn can_allows_granted_tier_and_everything_below_it() {
+        let mut session = SessionHandle::new("alice".to_string());
+        session.grant(1, Capability::Manage);
+
+        assert!(session.can(1, Capability::Read));
+        assert!(session.can(1, Capability::Use));
+        assert!(session.can(1, Capability::Manage));
+        assert!(!session.can(1, Capability::Admin));
+    }
+
+    #[test]
+    fn can_is_false_for_an_actuator_with_no_grant() {
+        let mut session = SessionHandle::new("alice".to_string());
+        session.grant(1, Capability::Admin);
+
+        assert!(!session.can(2, Capability::Read));
+    }
+
+    #[test]
+    fn revoke_removes_the_grant() {
+        let mut session = SessionHandle::new("alice".to_string());
+        session.grant(1, Capability::Admin);
+        session.revoke(1);
+
+        assert!(!session.can(1, Capability::Read));
+    }
+
+    #[test]
+    fn can_any_checks_across_every_granted_actuator() {
+        let mut session = SessionHandle::new("alice".to_string());
+        session.grant(1, Capability::Read);
+        session.grant(2, Capability::Admin);
+
+        assert!(session.can_any(Capability::Admin));
+        assert!(!SessionHandle::new("bob".to_string()).can_any(Capability::Read));
+    }
+}